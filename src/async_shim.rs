@@ -0,0 +1,157 @@
+//! Spawns and blocks on the futures that back [`PromiseJs`](crate::PromiseJs)
+//! and [`Ctx::spawn_cancellable`](crate::Ctx::spawn_cancellable).
+//!
+//! [`Executor`] is the seam between those and whatever is actually
+//! driving them: [`LocalExecutor`] (the default with neither the
+//! `tokio` nor `async-std` feature enabled) just pushes futures into a
+//! [`Runtime`](crate::Runtime)'s own [`TaskSet`], to be polled by
+//! [`Runtime::run_event_loop`](crate::Runtime::run_event_loop);
+//! [`TokioExecutor`] and [`AsyncStdExecutor`] push into the same
+//! `TaskSet` *and* keep a background task on their runtime polling it, so
+//! spawned futures make progress whether or not the embedder ever calls
+//! `run_event_loop` itself. Construct a `Runtime` with a different
+//! `Executor` via `Runtime::with_executor` to plug in something else
+//! entirely - a `wasm-bindgen-futures` microtask queue, `smol`, a
+//! hand-rolled loop.
+use crate::promise::TaskSet;
+use crate::SafeRef;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Spawns a future to run to completion, detached from the caller.
+pub trait Executor: Send + Sync {
+    fn spawn_local(&self, fut: Pin<Box<dyn Future<Output = ()>>>);
+}
+
+/// Picks the `Executor` a freshly constructed [`Runtime`](crate::Runtime)
+/// should use by default: `tokio` if enabled, else `async-std` if
+/// enabled, else [`LocalExecutor`].
+pub(crate) fn default_executor(tasks: SafeRef<TaskSet>) -> std::sync::Arc<dyn Executor> {
+    #[cfg(feature = "tokio")]
+    {
+        std::sync::Arc::new(TokioExecutor::new(tasks))
+    }
+    #[cfg(all(feature = "async-std", not(feature = "tokio")))]
+    {
+        std::sync::Arc::new(AsyncStdExecutor::new(tasks))
+    }
+    #[cfg(not(any(feature = "tokio", feature = "async-std")))]
+    {
+        std::sync::Arc::new(LocalExecutor::new(tasks))
+    }
+}
+
+/// Pushes spawned futures into a [`Runtime`](crate::Runtime)'s own
+/// [`TaskSet`], relying on the embedder to drive them via
+/// [`Runtime::run_event_loop`](crate::Runtime::run_event_loop).
+#[derive(Clone)]
+pub(crate) struct LocalExecutor {
+    tasks: SafeRef<TaskSet>,
+}
+
+impl LocalExecutor {
+    pub(crate) fn new(tasks: SafeRef<TaskSet>) -> Self {
+        Self { tasks }
+    }
+}
+
+impl Executor for LocalExecutor {
+    fn spawn_local(&self, fut: Pin<Box<dyn Future<Output = ()>>>) {
+        self.tasks.lock().push(fut);
+    }
+}
+
+/// Drains `tasks` in the background for as long as the returned future is
+/// polled, waking whenever a task completes or a new one is pushed.
+/// Shared by [`TokioExecutor`] and [`AsyncStdExecutor`], which each spawn
+/// one of these once (lazily) and let their own runtime poll it alongside
+/// everything else, so `TaskSet` entries make progress without the
+/// embedder having to await `run_event_loop` itself.
+fn drive_forever(tasks: SafeRef<TaskSet>) -> impl Future<Output = ()> {
+    futures::future::poll_fn(move |cx| {
+        while let std::task::Poll::Ready(Some(())) = tasks.lock().poll_next(cx) {}
+        std::task::Poll::Pending
+    })
+}
+
+#[cfg(feature = "tokio")]
+pub(crate) struct TokioExecutor {
+    tasks: SafeRef<TaskSet>,
+    driver_started: AtomicBool,
+}
+
+#[cfg(feature = "tokio")]
+impl TokioExecutor {
+    pub(crate) fn new(tasks: SafeRef<TaskSet>) -> Self {
+        Self {
+            tasks,
+            driver_started: AtomicBool::new(false),
+        }
+    }
+
+    fn ensure_driver(&self) {
+        if self.driver_started.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        tokio::task::spawn_local(drive_forever(self.tasks.clone()));
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Executor for TokioExecutor {
+    fn spawn_local(&self, fut: Pin<Box<dyn Future<Output = ()>>>) {
+        self.ensure_driver();
+        self.tasks.lock().push(fut);
+    }
+}
+
+#[cfg(feature = "async-std")]
+pub(crate) struct AsyncStdExecutor {
+    tasks: SafeRef<TaskSet>,
+    driver_started: AtomicBool,
+}
+
+#[cfg(feature = "async-std")]
+impl AsyncStdExecutor {
+    pub(crate) fn new(tasks: SafeRef<TaskSet>) -> Self {
+        Self {
+            tasks,
+            driver_started: AtomicBool::new(false),
+        }
+    }
+
+    fn ensure_driver(&self) {
+        if self.driver_started.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        async_std::task::spawn_local(drive_forever(self.tasks.clone()));
+    }
+}
+
+#[cfg(feature = "async-std")]
+impl Executor for AsyncStdExecutor {
+    fn spawn_local(&self, fut: Pin<Box<dyn Future<Output = ()>>>) {
+        self.ensure_driver();
+        self.tasks.lock().push(fut);
+    }
+}
+
+#[cfg(feature = "tokio")]
+pub fn block_on<F: Future>(fut: F) -> F::Output {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    // `TokioExecutor::spawn_local` calls `tokio::task::spawn_local`,
+    // which panics outside a `LocalSet` - run `fut` inside one so every
+    // `PromiseJs` spawned while it's awaited has somewhere to land.
+    tokio::task::LocalSet::new().block_on(&rt, fut)
+}
+
+#[cfg(all(feature = "async-std", not(feature = "tokio")))]
+pub fn block_on<F: Future>(fut: F) -> F::Output {
+    async_std::task::block_on(fut)
+}
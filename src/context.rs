@@ -0,0 +1,48 @@
+use crate::{qjs, Ctx, Error, Result, Runtime};
+
+/// A QuickJS execution context: its own global object, module registry,
+/// and class definitions, sharing a heap and job queue with the
+/// [`Runtime`] it was created from.
+///
+/// Dropping a `Context` aborts any outstanding [`PromiseJs`](crate::PromiseJs)
+/// or [`Ctx::spawn_cancellable`](crate::Ctx::spawn_cancellable) tasks still
+/// bound to it, so none of them can later try to settle a promise into a
+/// context that no longer exists.
+pub struct Context {
+    rt: Runtime,
+    ctx: *mut qjs::JSContext,
+}
+
+unsafe impl Send for Context {}
+
+impl Context {
+    /// Creates a new context with all intrinsics (the full standard
+    /// library) enabled.
+    pub fn full(rt: &Runtime) -> Result<Self> {
+        let ctx = unsafe { qjs::JS_NewContext(rt.as_raw()) };
+        if ctx.is_null() {
+            return Err(Error::Unknown);
+        }
+        Ok(Self {
+            rt: rt.clone(),
+            ctx,
+        })
+    }
+
+    /// Runs `f` with a [`Ctx`] borrowing this context, returning its
+    /// result.
+    pub fn with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(Ctx<'_>) -> R,
+    {
+        let _inner = self.rt.inner.lock();
+        f(Ctx::from_ptr(self.ctx))
+    }
+}
+
+impl Drop for Context {
+    fn drop(&mut self) {
+        self.rt.abort_tasks_for_context(self.ctx);
+        unsafe { qjs::JS_FreeContext(self.ctx) };
+    }
+}
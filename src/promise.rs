@@ -1,16 +1,91 @@
-#[cfg(feature = "deferred-resolution")]
 use crate::qjs;
 use crate::{
-    Ctx, Error, FromJs, Function, IntoJs, JsFn, Object, Persistent, Result, SafeRef,
+    Ctx, Error, FromJs, Function, IntoJs, JsFn, Object, Persistent, Result, Runtime, SafeRef,
     SendWhenParallel, This, Value,
 };
+use futures::{
+    future::{abortable, AbortHandle, Aborted},
+    stream::FuturesUnordered,
+    task::AtomicWaker,
+    Stream,
+};
 use std::{
     future::Future,
     mem,
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll, Waker},
 };
 
+/// Registry of futures spawned from [`PromiseJs`], owned by a [`Runtime`]
+/// and drained by [`Runtime::run_event_loop`].
+#[derive(Default)]
+pub(crate) struct TaskSet {
+    tasks: FuturesUnordered<Pin<Box<dyn Future<Output = ()>>>>,
+    waker: AtomicWaker,
+}
+
+impl TaskSet {
+    pub(crate) fn push(&mut self, task: Pin<Box<dyn Future<Output = ()>>>) {
+        self.tasks.push(task);
+        self.waker.wake();
+    }
+
+    /// Polls the next outstanding task to completion, registering `cx`'s
+    /// waker to be woken on the next completion or [`TaskSet::push`].
+    ///
+    /// Shared by [`RunEventLoop`] and the `tokio`/`async-std` executors'
+    /// background pump, so both ways of draining a [`TaskSet`] see the
+    /// same wakeups.
+    pub(crate) fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<()>> {
+        self.waker.register(cx.waker());
+        Pin::new(&mut self.tasks).poll_next(cx)
+    }
+}
+
+/// A future that drives a [`Runtime`] to quiescence.
+///
+/// Returned by [`Runtime::run_event_loop`]. Each poll drains QuickJS's
+/// pending job queue and polls every future spawned via [`PromiseJs`];
+/// it resolves once both are empty, so awaiting it is equivalent to
+/// looping on `rt.spawn_pending_jobs(None)` except it also drives Rust
+/// futures that are themselves waiting on a promise to settle.
+#[cfg_attr(feature = "doc-cfg", doc(cfg(feature = "futures")))]
+pub struct RunEventLoop<'rt> {
+    pub(crate) rt: &'rt Runtime,
+}
+
+impl Future for RunEventLoop<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let tasks = self.rt.tasks();
+
+        // Run every pending QuickJS job first: a job may settle a promise
+        // that one of the spawned tasks below is currently awaiting.
+        while self.rt.execute_pending_job() {}
+
+        loop {
+            let mut tasks = tasks.lock();
+            match tasks.poll_next(cx) {
+                Poll::Ready(Some(())) => {
+                    // A task just completed: it may have enqueued more
+                    // pending jobs (e.g. by resolving a promise), so drain
+                    // those before polling the remaining tasks again.
+                    drop(tasks);
+                    while self.rt.execute_pending_job() {}
+                }
+                Poll::Ready(None) if self.rt.has_pending_jobs() => {
+                    drop(tasks);
+                    while self.rt.execute_pending_job() {}
+                }
+                Poll::Ready(None) => return Poll::Ready(()),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
 /// Future-aware promise
 #[cfg_attr(feature = "doc-cfg", doc(cfg(feature = "futures")))]
 pub struct Promise<T> {
@@ -46,6 +121,11 @@ where
 {
     fn from_js(ctx: Ctx<'js>, value: Value<'js>) -> Result<Self> {
         let obj = Object::from_js(ctx, value)?;
+        // `obj.get("then")` already errors out cleanly (rather than
+        // panicking) if `then` is missing or not callable, so any object
+        // reaching past this point is a thenable per the spec's
+        // definition - whether or not it's a genuine `Promise` instance;
+        // use `Value::is_promise` to tell the two apart.
         let then: Function = obj.get("then")?;
         let state = SafeRef::new(State::default());
         let on_ok = JsFn::new("onSuccess", {
@@ -80,6 +160,89 @@ impl<T> Future for Promise<T> {
     }
 }
 
+/// A resolver handle for a JS `Promise` created via [`Ctx::promise_deferred`].
+///
+/// Unlike [`PromiseJs`], which settles a promise once the Rust future it
+/// wraps completes, a `Deferred` lets you create the promise up front and
+/// settle it later from wherever the result eventually arrives - for
+/// example a callback-based native API that has no `Future` of its own.
+/// `Deferred` owns no borrow of any particular [`Ctx`], so unlike values
+/// still tied to a `'js` lifetime, it can be moved across `Context::with`
+/// closures or stored in a struct. It holds a raw `JSContext` pointer and
+/// is not `Send`, so it stays on the thread that created it.
+#[cfg_attr(feature = "doc-cfg", doc(cfg(feature = "futures")))]
+pub struct Deferred {
+    ctx: *mut qjs::JSContext,
+    promise: Persistent<Value<'static>>,
+    then: Option<Persistent<Function<'static>>>,
+    catch: Option<Persistent<Function<'static>>>,
+}
+
+impl Deferred {
+    fn new<'js>(ctx: Ctx<'js>, promise: Value<'js>, then: Function<'js>, catch: Function<'js>) -> Self {
+        Self {
+            ctx: ctx.ctx,
+            promise: Persistent::save(ctx, promise),
+            then: Some(Persistent::save(ctx, then)),
+            catch: Some(Persistent::save(ctx, catch)),
+        }
+    }
+
+    /// The `Promise` this deferred will eventually settle.
+    pub fn promise<'js>(&self, ctx: Ctx<'js>) -> Value<'js> {
+        self.promise.clone().restore(ctx).unwrap()
+    }
+
+    /// Resolves the promise with `value`.
+    ///
+    /// `resolve` and [`reject`](Self::reject) both take the `Deferred` by
+    /// value, so a `Deferred` settles its promise at most once.
+    pub fn resolve<V>(mut self, value: V) -> Result<()>
+    where
+        V: for<'js> IntoJs<'js>,
+    {
+        let Some(then) = self.then.take() else {
+            return Ok(());
+        };
+        let catch = self.catch.take().unwrap();
+        let ctx = Ctx::from_ptr(self.ctx);
+
+        match value.into_js(ctx) {
+            Ok(value) => resolve(ctx, then.restore(ctx)?, value),
+            // Mirror spawn_promise: a conversion failure on the success
+            // path still settles the promise - as a rejection - instead
+            // of leaving it pending forever.
+            Err(error) => resolve(ctx, catch.restore(ctx)?, error.into_js(ctx)?),
+        }
+        Ok(())
+    }
+
+    /// Rejects the promise with `error`.
+    pub fn reject<V>(mut self, error: V) -> Result<()>
+    where
+        V: for<'js> IntoJs<'js>,
+    {
+        self.then.take();
+        let Some(catch) = self.catch.take() else {
+            return Ok(());
+        };
+        let ctx = Ctx::from_ptr(self.ctx);
+        let error = error.into_js(ctx)?;
+        resolve(ctx, catch.restore(ctx)?, error);
+        Ok(())
+    }
+}
+
+impl<'js> Ctx<'js> {
+    /// Creates a pending `Promise` together with a [`Deferred`] handle
+    /// that can settle it at a later point, independent of any `Future`.
+    #[cfg_attr(feature = "doc-cfg", doc(cfg(feature = "futures")))]
+    pub fn promise_deferred(self) -> Result<Deferred> {
+        let (promise, then, catch) = self.promise()?;
+        Ok(Deferred::new(self, promise.into_value(), then, catch))
+    }
+}
+
 /// Wrapper for futures to convert to JS promises
 #[cfg_attr(feature = "doc-cfg", doc(cfg(feature = "futures")))]
 #[repr(transparent)]
@@ -91,50 +254,155 @@ impl<T> From<T> for PromiseJs<T> {
     }
 }
 
-#[cfg(any(feature = "async-std", feature = "tokio"))]
-impl<'js, T> IntoJs<'js> for PromiseJs<T>
+/// Guard returned by [`Ctx::spawn_cancellable`].
+///
+/// Dropping it aborts the associated future if it hasn't settled its
+/// promise yet; the promise is then left permanently pending rather than
+/// being resolved or rejected.
+#[cfg_attr(feature = "doc-cfg", doc(cfg(feature = "futures")))]
+pub struct AbortOnDrop(AbortHandle);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Spawns `future` as a promise-settling task, registering an
+/// [`AbortHandle`] in the runtime's task slab so it gets cancelled - left
+/// pending rather than resolved into a potentially-dead context - if the
+/// `Runtime` or `Context` is torn down before it completes.
+fn spawn_promise<'js, T>(ctx: Ctx<'js>, future: T) -> Result<(Value<'js>, AbortHandle)>
 where
     T: Future + 'static,
     T::Output: IntoJs<'js> + 'static,
 {
-    fn into_js(self, ctx: Ctx<'js>) -> Result<Value<'js>> {
-        let (promise, then, catch) = ctx.promise()?;
-
-        let then = Persistent::save(ctx, then);
-        let catch = Persistent::save(ctx, catch);
-
-        let runtime = unsafe { &ctx.get_opaque().runtime }
-            .try_ref()
-            .ok_or(Error::Unknown)?;
+    let (promise, then, catch) = ctx.promise()?;
+
+    let then = Persistent::save(ctx, then);
+    let catch = Persistent::save(ctx, catch);
+
+    let runtime = unsafe { &ctx.get_opaque().runtime }
+        .try_ref()
+        .ok_or(Error::Unknown)?;
+
+    let ctx = ctx.ctx;
+    let (future, abort_handle) = abortable(future);
+    let task_id = runtime.register_task_abort(ctx, abort_handle.clone());
+
+    // Captured as a weak handle so this task - owned by `runtime`'s own
+    // executor/task slab for as long as it runs - doesn't keep `runtime`
+    // alive forever in a reference cycle.
+    let weak_runtime = Arc::downgrade(&runtime.0);
+
+    let executor = runtime.executor().clone();
+    executor.spawn_local(Box::pin(async move {
+        let result = future.await;
+
+        // The runtime may already be gone by the time this task finishes
+        // - e.g. every external handle was dropped while this was the
+        // last thing keeping the event loop busy. There's then nothing
+        // left to settle the promise into.
+        let Some(runtime) = weak_runtime.upgrade().map(Runtime) else {
+            return;
+        };
+        // Whether this settles normally or was aborted, its slab entry
+        // is done with - unregistering here covers both the normal-
+        // completion case and a manually-dropped `AbortOnDrop` guard;
+        // `abort_tasks_for_context`/`RuntimeData`'s `Drop` already
+        // removed it for the context/runtime-teardown case, so this is
+        // a harmless no-op then.
+        runtime.unregister_task_abort(task_id);
+
+        let result = match result {
+            Ok(result) => result,
+            // Aborted: the `AbortOnDrop` guard was dropped, or the
+            // runtime/context tore down first. Either way `ctx` may no
+            // longer be valid, so leave the promise pending.
+            Err(Aborted) => return,
+        };
+
+        let rt_lock = runtime.inner.lock();
+        let ctx = Ctx::from_ptr(ctx);
+
+        match result.into_js(ctx) {
+            Ok(value) => {
+                mem::drop(catch);
+                resolve(ctx, then.restore(ctx).unwrap(), value)
+            }
+            Err(error) => {
+                mem::drop(then);
+                resolve(
+                    ctx,
+                    catch.restore(ctx).unwrap(),
+                    error.into_js(ctx).unwrap(),
+                )
+            }
+        };
 
-        let ctx = ctx.ctx;
-        let future = self.0;
+        mem::drop(rt_lock);
+    }));
 
-        crate::async_shim::spawn_local(async move {
-            let result = future.await;
+    Ok((promise.into_value(), abort_handle))
+}
 
-            let rt_lock = runtime.inner.lock();
-            let ctx = Ctx::from_ptr(ctx);
+impl<'js, T> IntoJs<'js> for PromiseJs<T>
+where
+    T: Future + 'static,
+    T::Output: IntoJs<'js> + 'static,
+{
+    fn into_js(self, ctx: Ctx<'js>) -> Result<Value<'js>> {
+        let (value, _abort_handle) = spawn_promise(ctx, self.0)?;
+        Ok(value)
+    }
+}
 
-            match result.into_js(ctx) {
-                Ok(value) => {
-                    mem::drop(catch);
-                    resolve(ctx, then.restore(ctx).unwrap(), value)
-                }
-                Err(error) => {
-                    mem::drop(then);
-                    resolve(
-                        ctx,
-                        catch.restore(ctx).unwrap(),
-                        error.into_js(ctx).unwrap(),
-                    )
-                }
-            };
+impl<'js> Ctx<'js> {
+    /// Spawns `future` the same way [`PromiseJs`] would, but also returns
+    /// a guard that cancels it early: dropping the returned
+    /// [`AbortOnDrop`] aborts the future if it hasn't completed, leaving
+    /// its promise permanently pending.
+    #[cfg_attr(feature = "doc-cfg", doc(cfg(feature = "futures")))]
+    pub fn spawn_cancellable<T>(self, future: T) -> Result<(Value<'js>, AbortOnDrop)>
+    where
+        T: Future + 'static,
+        T::Output: IntoJs<'js> + 'static,
+    {
+        let (value, handle) = spawn_promise(self, future)?;
+        Ok((value, AbortOnDrop(handle)))
+    }
 
-            mem::drop(rt_lock);
-        });
+    /// Adopts `value` into a [`Promise`], wrapping it with
+    /// `Promise.resolve(value)` first.
+    ///
+    /// Unlike [`Promise::from_js`], which requires `value` to already be
+    /// a thenable, this accepts *any* value - including a genuine
+    /// `Promise`, which `Promise.resolve` passes through unchanged - so
+    /// callers can await a JS function's return value uniformly without
+    /// checking [`Value::is_promise`] first.
+    #[cfg_attr(feature = "doc-cfg", doc(cfg(feature = "futures")))]
+    pub fn resolve_thenable<T>(self, value: Value<'js>) -> Result<Promise<T>>
+    where
+        T: FromJs<'js> + SendWhenParallel + 'static,
+    {
+        let promise_ctor: Object = self.globals().get("Promise")?;
+        let resolve: Function = promise_ctor.get("resolve")?;
+        let resolved: Value = resolve.call((This(promise_ctor), value))?;
+        Promise::from_js(self, resolved)
+    }
+}
 
-        Ok(promise.into_value())
+impl<'js> Value<'js> {
+    /// Returns `true` if this value is a native `Promise` instance.
+    ///
+    /// A thenable object - anything with a callable `then` property, the
+    /// shape [`Promise::from_js`] accepts - is not necessarily a genuine
+    /// `Promise`; use this when the distinction matters, e.g. before
+    /// deciding whether [`Ctx::resolve_thenable`] needs to adopt a value
+    /// first.
+    #[cfg_attr(feature = "doc-cfg", doc(cfg(feature = "futures")))]
+    pub fn is_promise(&self) -> bool {
+        unsafe { qjs::JS_IsPromise(self.as_js_value()) != 0 }
     }
 }
 
@@ -174,6 +442,27 @@ unsafe extern "C" fn resolution_job(
     qjs::JS_Call(ctx, func, this, argc, argv)
 }
 
+/// Callback installed via [`Runtime::set_promise_rejection_tracker`].
+///
+/// Invoked with `is_handled == false` the moment a promise rejects with
+/// no handler attached, and again with `is_handled == true` if a handler
+/// is attached afterwards - mirroring Node's `unhandledRejection` /
+/// `rejectionHandled` events.
+pub(crate) type PromiseRejectionTracker = Box<dyn FnMut(Ctx<'_>, Value<'_>, bool)>;
+
+pub(crate) unsafe extern "C" fn promise_rejection_trampoline(
+    ctx: *mut qjs::JSContext,
+    _promise: qjs::JSValue,
+    reason: qjs::JSValue,
+    is_handled: qjs::c_int,
+    opaque: *mut std::ffi::c_void,
+) {
+    let tracker = &mut *(opaque as *mut PromiseRejectionTracker);
+    let ctx = Ctx::from_ptr(ctx);
+    let reason = Value::from_js_value(ctx, qjs::JS_DupValue(ctx.ctx, reason));
+    tracker(ctx, reason, is_handled != 0);
+}
+
 #[cfg(all(test, any(feature = "async-std", feature = "tokio")))]
 mod test {
     use crate::{async_shim::block_on, *};
@@ -204,37 +493,60 @@ mod test {
     }
 
     #[test]
-    #[ignore] // TODO:
     fn async_fn_unhandled_promise() {
         block_on(async {
-            async fn doit() {}
+            async fn doit() -> std::result::Result<(), Error> {
+                Err(Error::Unknown)
+            }
 
             let rt = Runtime::new().unwrap();
             let ctx = Context::full(&rt).unwrap();
 
-            rt.spawn_pending_jobs(None);
+            let unhandled = SafeRef::new(false);
+            rt.set_promise_rejection_tracker({
+                let unhandled = unhandled.clone();
+                move |_ctx, _reason, is_handled| {
+                    *unhandled.lock() = !is_handled;
+                }
+            });
 
             ctx.with(|ctx| {
                 let global = ctx.globals();
                 global
                     .set("doit", JsFn::new("doit", || PromiseJs(doit())))
                     .unwrap();
+                // Nothing subscribes to the returned promise on the JS
+                // side, so its rejection is genuinely unhandled.
                 let _ = ctx.eval::<Value, _>("doit()").unwrap();
             });
+
+            rt.run_event_loop().await;
+
+            assert!(*unhandled.lock());
         });
     }
 
     #[test]
-    #[ignore] // TODO:
     fn async_fn_unhandled_promise_future() {
         block_on(async {
-            async fn doit() {}
+            async fn doit() -> std::result::Result<(), Error> {
+                Err(Error::Unknown)
+            }
 
             let rt = Runtime::new().unwrap();
             let ctx = Context::full(&rt).unwrap();
 
-            rt.spawn_pending_jobs(None);
+            let unhandled = SafeRef::new(false);
+            rt.set_promise_rejection_tracker({
+                let unhandled = unhandled.clone();
+                move |_ctx, _reason, is_handled| {
+                    *unhandled.lock() = !is_handled;
+                }
+            });
 
+            // Converting to `Promise<()>` attaches a `then`/`catch` pair
+            // immediately, so the rejection is handled even though Rust
+            // never polls the returned future.
             let _res: Promise<()> = ctx.with(|ctx| {
                 let global = ctx.globals();
                 global
@@ -242,6 +554,62 @@ mod test {
                     .unwrap();
                 ctx.eval("doit()").unwrap()
             });
+
+            rt.run_event_loop().await;
+
+            assert!(!*unhandled.lock());
+        });
+    }
+
+    #[test]
+    fn value_is_promise() {
+        block_on(async {
+            let rt = Runtime::new().unwrap();
+            let ctx = Context::full(&rt).unwrap();
+
+            ctx.with(|ctx| {
+                let promise: Value = ctx.eval("Promise.resolve(1)").unwrap();
+                assert!(promise.is_promise());
+
+                let thenable: Value = ctx.eval("({ then() {} })").unwrap();
+                assert!(!thenable.is_promise());
+            });
+        });
+    }
+
+    #[test]
+    fn resolve_thenable_adopts_non_promise_value() {
+        block_on(async {
+            let rt = Runtime::new().unwrap();
+            let ctx = Context::full(&rt).unwrap();
+
+            let res: Promise<i32> = ctx.with(|ctx| {
+                let value: Value = ctx.eval("7").unwrap();
+                ctx.resolve_thenable(value).unwrap()
+            });
+
+            rt.spawn_pending_jobs(None);
+
+            assert_eq!(res.await.unwrap(), 7);
+        });
+    }
+
+    #[test]
+    fn resolve_thenable_adopts_rejected_thenable() {
+        block_on(async {
+            let rt = Runtime::new().unwrap();
+            let ctx = Context::full(&rt).unwrap();
+
+            let res: Promise<()> = ctx.with(|ctx| {
+                let thenable: Value = ctx
+                    .eval("({ then(_resolve, reject) { reject('nope') } })")
+                    .unwrap();
+                ctx.resolve_thenable(thenable).unwrap()
+            });
+
+            rt.spawn_pending_jobs(None);
+
+            assert!(res.await.is_err());
         });
     }
 }
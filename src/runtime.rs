@@ -0,0 +1,256 @@
+use crate::{
+    async_shim::Executor,
+    promise::{promise_rejection_trampoline, PromiseRejectionTracker, RunEventLoop, TaskSet},
+    qjs, Ctx, Error, Result, SafeRef, Value,
+};
+use futures::future::AbortHandle;
+use std::{collections::HashMap, sync::Arc};
+
+pub(crate) struct Inner {
+    pub(crate) rt: *mut qjs::JSRuntime,
+}
+
+unsafe impl Send for Inner {}
+
+/// Identifies one [`register_task_abort`](Runtime::register_task_abort)
+/// entry, so the task it was registered for can remove its own slot again
+/// once it settles instead of leaking it until the next `Context`/`Runtime`
+/// teardown.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct TaskAbortId(u64);
+
+#[derive(Default)]
+struct TaskAborts {
+    next_id: u64,
+    entries: HashMap<TaskAbortId, (*mut qjs::JSContext, AbortHandle)>,
+}
+
+pub(crate) struct RuntimeData {
+    pub(crate) inner: SafeRef<Inner>,
+    tasks: SafeRef<TaskSet>,
+    executor: Arc<dyn Executor>,
+    rejection_tracker: SafeRef<Option<*mut std::ffi::c_void>>,
+    // Outstanding `PromiseJs`/`spawn_cancellable` tasks, each stored with
+    // the `JSContext` it'll resolve its promise into, so a dropped
+    // `Context` can cancel just its own tasks and a dropped `Runtime` can
+    // cancel everything that's left.
+    task_aborts: SafeRef<TaskAborts>,
+}
+
+/// An instance of the QuickJS runtime: the heap, job queue, and (via
+/// [`PromiseJs`](crate::PromiseJs)) any futures spawned from it.
+#[derive(Clone)]
+pub struct Runtime(pub(crate) Arc<RuntimeData>);
+
+impl std::ops::Deref for Runtime {
+    type Target = RuntimeData;
+
+    fn deref(&self) -> &RuntimeData {
+        &self.0
+    }
+}
+
+impl Runtime {
+    /// Creates a new runtime using the default [`Executor`] for whichever
+    /// of the `tokio`/`async-std` features is enabled, falling back to
+    /// driving [`PromiseJs`](crate::PromiseJs) futures from
+    /// [`Runtime::run_event_loop`] when neither is.
+    pub fn new() -> Result<Self> {
+        let tasks = SafeRef::new(TaskSet::default());
+        let executor = crate::async_shim::default_executor(tasks.clone());
+        Self::new_with(executor, tasks)
+    }
+
+    /// Creates a new runtime that spawns [`PromiseJs`](crate::PromiseJs)
+    /// futures through `executor` instead of the feature-selected
+    /// default - for embedders driving their own event loop (a
+    /// `wasm-bindgen-futures` microtask queue, `smol`, a hand-rolled
+    /// single-threaded loop).
+    ///
+    /// Those futures are driven by `executor` alone: for a runtime built
+    /// this way, [`Runtime::run_event_loop`] runs only the pending QuickJS
+    /// job queue and does not poll them.
+    #[cfg_attr(feature = "doc-cfg", doc(cfg(feature = "futures")))]
+    pub fn with_executor<E>(executor: E) -> Result<Self>
+    where
+        E: Executor + 'static,
+    {
+        Self::new_with(Arc::new(executor), SafeRef::new(TaskSet::default()))
+    }
+
+    fn new_with(executor: Arc<dyn Executor>, tasks: SafeRef<TaskSet>) -> Result<Self> {
+        let rt = unsafe { qjs::JS_NewRuntime() };
+        if rt.is_null() {
+            return Err(Error::Unknown);
+        }
+
+        Ok(Self(Arc::new(RuntimeData {
+            inner: SafeRef::new(Inner { rt }),
+            tasks,
+            executor,
+            rejection_tracker: SafeRef::new(None),
+            task_aborts: SafeRef::new(TaskAborts::default()),
+        })))
+    }
+
+    /// The task set that [`PromiseJs`](crate::PromiseJs) pushes spawned
+    /// futures into; drained by [`Runtime::run_event_loop`].
+    pub(crate) fn tasks(&self) -> &SafeRef<TaskSet> {
+        &self.tasks
+    }
+
+    /// The [`Executor`] this runtime spawns [`PromiseJs`](crate::PromiseJs)
+    /// futures onto.
+    pub(crate) fn executor(&self) -> Arc<dyn Executor> {
+        self.executor.clone()
+    }
+
+    /// Runs a single pending QuickJS job, if any.
+    ///
+    /// Returns `true` if a job ran, `false` if the queue was empty.
+    pub(crate) fn execute_pending_job(&self) -> bool {
+        let inner = self.inner.lock();
+        let mut job_ctx = std::ptr::null_mut();
+        let ret = unsafe { qjs::JS_ExecutePendingJob(inner.rt, &mut job_ctx) };
+        if ret < 0 {
+            // The job threw: the exception is cleared from `job_ctx` and
+            // dropped without being reported.
+            unsafe { qjs::JS_FreeValue(job_ctx, qjs::JS_GetException(job_ctx)) };
+        }
+        ret != 0
+    }
+
+    /// Returns whether QuickJS's pending job queue has anything left to
+    /// run.
+    pub(crate) fn has_pending_jobs(&self) -> bool {
+        let inner = self.inner.lock();
+        unsafe { qjs::JS_IsJobPending(inner.rt) != 0 }
+    }
+
+    /// Pumps the pending job queue, running up to `limit` jobs (or until
+    /// it's empty, if `None`). Returns the number of jobs run.
+    pub fn spawn_pending_jobs(&self, limit: Option<usize>) -> usize {
+        let mut ran = 0;
+        while limit.map_or(true, |limit| ran < limit) && self.execute_pending_job() {
+            ran += 1;
+        }
+        ran
+    }
+
+    /// Returns a future that drives this runtime's pending QuickJS jobs
+    /// and [`PromiseJs`](crate::PromiseJs) futures spawned through it to
+    /// completion.
+    ///
+    /// Await it directly to run until there is nothing left to do, or
+    /// poll it alongside other work to cooperatively pump the runtime.
+    #[cfg_attr(feature = "doc-cfg", doc(cfg(feature = "futures")))]
+    pub fn run_event_loop(&self) -> RunEventLoop<'_> {
+        RunEventLoop { rt: self }
+    }
+
+    /// The raw `JSRuntime` pointer, for handing to QuickJS APIs that take
+    /// one directly.
+    pub(crate) fn as_raw(&self) -> *mut qjs::JSRuntime {
+        self.inner.lock().rt
+    }
+
+    /// Stashes `opaque` as the current promise rejection tracker, handing
+    /// back whatever was stashed before it (if any) so the caller can
+    /// free it.
+    pub(crate) fn replace_rejection_tracker_opaque(
+        &self,
+        opaque: *mut std::ffi::c_void,
+    ) -> Option<*mut std::ffi::c_void> {
+        self.rejection_tracker.lock().replace(opaque)
+    }
+
+    /// Installs a hook that observes promise rejections with no attached
+    /// handler.
+    ///
+    /// QuickJS calls the tracker once when a promise rejection first goes
+    /// unhandled, and again if a handler is later attached to it. Only
+    /// one tracker can be installed at a time; installing a new one
+    /// replaces (and drops) the previous one.
+    #[cfg_attr(feature = "doc-cfg", doc(cfg(feature = "futures")))]
+    pub fn set_promise_rejection_tracker<F>(&self, tracker: F)
+    where
+        F: FnMut(Ctx<'_>, Value<'_>, bool) + 'static,
+    {
+        let opaque: *mut PromiseRejectionTracker = Box::into_raw(Box::new(Box::new(tracker)));
+
+        unsafe {
+            if let Some(previous) = self.replace_rejection_tracker_opaque(opaque.cast()) {
+                drop(Box::from_raw(previous as *mut PromiseRejectionTracker));
+            }
+            qjs::JS_SetHostPromiseRejectionTracker(
+                self.as_raw(),
+                Some(promise_rejection_trampoline),
+                opaque.cast(),
+            );
+        }
+    }
+
+    /// Registers `handle` in this runtime's task slab, so it gets aborted
+    /// automatically if `ctx` (or the runtime itself) is dropped before
+    /// the task completes. Returns an id the caller must pass to
+    /// [`unregister_task_abort`](Self::unregister_task_abort) once the
+    /// task settles on its own, so the slab doesn't grow unbounded.
+    pub(crate) fn register_task_abort(
+        &self,
+        ctx: *mut qjs::JSContext,
+        handle: AbortHandle,
+    ) -> TaskAbortId {
+        let mut aborts = self.task_aborts.lock();
+        let id = TaskAbortId(aborts.next_id);
+        aborts.next_id += 1;
+        aborts.entries.insert(id, (ctx, handle));
+        id
+    }
+
+    /// Removes a task's slab entry once it has settled its promise on its
+    /// own, without aborting it.
+    pub(crate) fn unregister_task_abort(&self, id: TaskAbortId) {
+        self.task_aborts.lock().entries.remove(&id);
+    }
+
+    /// Aborts and forgets every task registered against `ctx`, without
+    /// touching tasks belonging to other contexts on this runtime.
+    pub(crate) fn abort_tasks_for_context(&self, ctx: *mut qjs::JSContext) {
+        let mut aborts = self.task_aborts.lock();
+        aborts.entries.retain(|_, (task_ctx, handle)| {
+            if *task_ctx == ctx {
+                handle.abort();
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
+
+impl Drop for RuntimeData {
+    fn drop(&mut self) {
+        for (_, handle) in self.task_aborts.lock().entries.drain() {
+            handle.abort();
+        }
+
+        // Tell QuickJS to forget the tracker callback before freeing its
+        // opaque pointer below - otherwise a promise finalized during
+        // `JS_FreeRuntime` could call back through a pointer we've
+        // already deallocated.
+        let opaque = self.rejection_tracker.lock().take();
+        if opaque.is_some() {
+            let rt = self.inner.lock().rt;
+            unsafe { qjs::JS_SetHostPromiseRejectionTracker(rt, None, std::ptr::null_mut()) };
+        }
+
+        {
+            let inner = self.inner.lock();
+            unsafe { qjs::JS_FreeRuntime(inner.rt) };
+        }
+
+        if let Some(opaque) = opaque {
+            unsafe { drop(Box::from_raw(opaque as *mut PromiseRejectionTracker)) };
+        }
+    }
+}